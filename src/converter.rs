@@ -1,10 +1,52 @@
 use image::{imageops::FilterType, DynamicImage, GenericImage, GenericImageView, Rgba};
 use imagequant::RGBA;
-use oklab::{srgb_to_oklab, RGB};
+use oklab::{srgb_to_oklab, Oklab, RGB};
+use std::error;
 use std::fmt::Display;
+use std::path::Path;
+use std::sync::OnceLock;
 
 static BLACK: [u8; 4] = [0, 0, 0, 255];
 
+/// Maps each of the 256 font slots to its CP437 Unicode equivalent, so a
+/// rasterized TrueType font fills the same glyph slots (box drawing, blocks,
+/// etc.) the rest of the pipeline assumes — e.g. 219 `█` and 223 `▀`.
+#[rustfmt::skip]
+static CP437: [char; 256] = [
+    '\u{0000}', '\u{263A}', '\u{263B}', '\u{2665}', '\u{2666}', '\u{2663}', '\u{2660}', '\u{2022}',
+    '\u{25D8}', '\u{25CB}', '\u{25D9}', '\u{2642}', '\u{2640}', '\u{266A}', '\u{266B}', '\u{263C}',
+    '\u{25BA}', '\u{25C4}', '\u{2195}', '\u{203C}', '\u{00B6}', '\u{00A7}', '\u{25AC}', '\u{21A8}',
+    '\u{2191}', '\u{2193}', '\u{2192}', '\u{2190}', '\u{221F}', '\u{2194}', '\u{25B2}', '\u{25BC}',
+    ' ', '!', '"', '#', '$', '%', '&', '\'',
+    '(', ')', '*', '+', ',', '-', '.', '/',
+    '0', '1', '2', '3', '4', '5', '6', '7',
+    '8', '9', ':', ';', '<', '=', '>', '?',
+    '@', 'A', 'B', 'C', 'D', 'E', 'F', 'G',
+    'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O',
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W',
+    'X', 'Y', 'Z', '[', '\\', ']', '^', '_',
+    '`', 'a', 'b', 'c', 'd', 'e', 'f', 'g',
+    'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o',
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w',
+    'x', 'y', 'z', '{', '|', '}', '~', '\u{2302}',
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}',
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}',
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}',
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00A2}', '\u{00A3}', '\u{00A5}', '\u{20A7}', '\u{0192}',
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}',
+    '\u{00BF}', '\u{2310}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}',
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}',
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{255C}', '\u{255B}', '\u{2510}',
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{255E}', '\u{255F}',
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{2567}',
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256B}',
+    '\u{256A}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{258C}', '\u{2590}', '\u{2580}',
+    '\u{03B1}', '\u{00DF}', '\u{0393}', '\u{03C0}', '\u{03A3}', '\u{03C3}', '\u{00B5}', '\u{03C4}',
+    '\u{03A6}', '\u{0398}', '\u{03A9}', '\u{03B4}', '\u{221E}', '\u{03C6}', '\u{03B5}', '\u{2229}',
+    '\u{2261}', '\u{00B1}', '\u{2265}', '\u{2264}', '\u{2320}', '\u{2321}', '\u{00F7}', '\u{2248}',
+    '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
+];
+
 static CGA_PALETTE: [RGB<u8>; 16] = [
     RGB::new(0, 0, 0),
     RGB::new(170, 0, 0),
@@ -29,26 +71,215 @@ pub fn get_cga_color(index: u8) -> [u8; 4] {
     [cga_color.r, cga_color.g, cga_color.b, 255]
 }
 
+/// The CGA palette as 48 bytes of 6-bit RGB triplets, the layout XBin embeds
+/// when carrying its own palette.
+pub fn cga_palette_xbin() -> Vec<u8> {
+    CGA_PALETTE
+        .iter()
+        .flat_map(|color| [color.r >> 2, color.g >> 2, color.b >> 2])
+        .collect()
+}
+
+/// The standard xterm 256-color palette: the 16 system colors, the 6×6×6 color
+/// cube, and the 24-step grayscale ramp. Built once on first use.
+fn xterm_palette() -> &'static [[u8; 3]; 256] {
+    static CACHE: OnceLock<[[u8; 3]; 256]> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        const SYSTEM: [[u8; 3]; 16] = [
+            [0, 0, 0],
+            [128, 0, 0],
+            [0, 128, 0],
+            [128, 128, 0],
+            [0, 0, 128],
+            [128, 0, 128],
+            [0, 128, 128],
+            [192, 192, 192],
+            [128, 128, 128],
+            [255, 0, 0],
+            [0, 255, 0],
+            [255, 255, 0],
+            [0, 0, 255],
+            [255, 0, 255],
+            [0, 255, 255],
+            [255, 255, 255],
+        ];
+        let levels = [0u8, 95, 135, 175, 215, 255];
+        let mut palette = [[0u8; 3]; 256];
+        palette[..16].copy_from_slice(&SYSTEM);
+        let mut index = 16;
+        for &r in &levels {
+            for &g in &levels {
+                for &b in &levels {
+                    palette[index] = [r, g, b];
+                    index += 1;
+                }
+            }
+        }
+        for step in 0..24u8 {
+            let gray = 8 + step * 10;
+            palette[index] = [gray, gray, gray];
+            index += 1;
+        }
+        palette
+    })
+}
+
+/// The oklab coordinates of the xterm palette, memoized alongside it so that
+/// `find_closest_xterm_color` never re-runs `srgb_to_oklab` over 256 entries
+/// per block (mirroring [`cga_palette_oklab`]).
+fn xterm_palette_oklab() -> &'static [Oklab; 256] {
+    static CACHE: OnceLock<[Oklab; 256]> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        std::array::from_fn(|index| {
+            let entry = xterm_palette()[index];
+            srgb_to_oklab(RGB::new(entry[0], entry[1], entry[2]))
+        })
+    })
+}
+
+pub fn get_xterm_color(index: u8) -> [u8; 4] {
+    let color = xterm_palette()[index as usize];
+    [color[0], color[1], color[2], 255]
+}
+
+pub fn find_closest_xterm_color(color: [u8; 4]) -> u8 {
+    let color_ok = srgb_to_oklab(RGB::new(color[0], color[1], color[2]));
+    let mut best = 0usize;
+    let mut best_distance = f32::MAX;
+    for (index, entry_ok) in xterm_palette_oklab().iter().enumerate() {
+        let distance = oklab_distance(&color_ok, entry_ok);
+        if distance < best_distance {
+            best = index;
+            best_distance = distance;
+        }
+    }
+    best as u8
+}
+
+fn oklab_distance(a: &Oklab, b: &Oklab) -> f32 {
+    (a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)
+}
+
+/// The oklab coordinates of the 16 CGA entries, computed once on first use so
+/// that `find_closest_cga_color` does not re-run `srgb_to_oklab` over the whole
+/// palette for every block of a large image.
+fn cga_palette_oklab() -> &'static [Oklab; 16] {
+    static CACHE: OnceLock<[Oklab; 16]> = OnceLock::new();
+    CACHE.get_or_init(|| {
+        std::array::from_fn(|index| {
+            let cga_color = CGA_PALETTE[index];
+            srgb_to_oklab(RGB::new(cga_color.r, cga_color.g, cga_color.b))
+        })
+    })
+}
+
 pub fn find_closest_cga_color(color: [u8; 4]) -> u8 {
-    let mut best: Option<usize> = None;
-    let mut best_distance: Option<f32> = None;
     let color_ok = srgb_to_oklab(RGB::new(color[0], color[1], color[2]));
-    for (index, cga_color) in CGA_PALETTE.iter().enumerate() {
-        let cga_color_ok = srgb_to_oklab(RGB::new(cga_color.r, cga_color.g, cga_color.b));
-        let distance = (color_ok.l - cga_color_ok.l).powi(2)
-            + (color_ok.a - cga_color_ok.a).powi(2)
-            + (color_ok.b - cga_color_ok.b).powi(2);
-        if let Some(best_value) = best_distance.as_mut() {
-            if distance < *best_value {
-                best = Some(index);
-                *best_value = distance;
+    let mut best = 0usize;
+    let mut best_distance = f32::MAX;
+    for (index, cga_color_ok) in cga_palette_oklab().iter().enumerate() {
+        let distance = oklab_distance(&color_ok, cga_color_ok);
+        if distance < best_distance {
+            best = index;
+            best_distance = distance;
+        }
+    }
+    best as u8
+}
+
+fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(channel: f32) -> u8 {
+    let c = channel.clamp(0.0, 1.0);
+    let v = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+/// Quantizes `color`, offset by the accumulated diffusion `error`, to the
+/// nearest CGA entry and returns that index together with the residual error
+/// (error-adjusted color minus chosen color). The error is carried in linear RGB so the
+/// diffusion preserves perceived brightness across gradients.
+fn quantize_with_error(color: [u8; 4], error: [f32; 3]) -> (u8, [f32; 3]) {
+    let adjusted = [
+        (srgb_to_linear(color[0]) + error[0]).clamp(0.0, 1.0),
+        (srgb_to_linear(color[1]) + error[1]).clamp(0.0, 1.0),
+        (srgb_to_linear(color[2]) + error[2]).clamp(0.0, 1.0),
+    ];
+    let index = find_closest_cga_color([
+        linear_to_srgb(adjusted[0]),
+        linear_to_srgb(adjusted[1]),
+        linear_to_srgb(adjusted[2]),
+        255,
+    ]);
+    let chosen = CGA_PALETTE[index as usize];
+    let residual = [
+        adjusted[0] - srgb_to_linear(chosen.r),
+        adjusted[1] - srgb_to_linear(chosen.g),
+        adjusted[2] - srgb_to_linear(chosen.b),
+    ];
+    (index, residual)
+}
+
+/// Distributes quantization `error` to the not-yet-processed neighbours of the
+/// cell at (`column`, `row`) with the standard Floyd–Steinberg weights.
+fn diffuse_error(
+    buffer: &mut [[f32; 3]],
+    column: u32,
+    row: u32,
+    columns: u32,
+    rows: u32,
+    error: [f32; 3],
+) {
+    let mut add = |c: i64, r: i64, weight: f32| {
+        if c < 0 || c >= columns as i64 || r >= rows as i64 {
+            return;
+        }
+        let cell = &mut buffer[(r as u32 * columns + c as u32) as usize];
+        for channel in 0..3 {
+            cell[channel] += error[channel] * weight;
+        }
+    };
+    let (c, r) = (column as i64, row as i64);
+    add(c + 1, r, 7.0 / 16.0);
+    add(c - 1, r + 1, 3.0 / 16.0);
+    add(c, r + 1, 5.0 / 16.0);
+    add(c + 1, r + 1, 1.0 / 16.0);
+}
+
+/// Applies Floyd–Steinberg error diffusion to the CGA mapping of each block,
+/// dithering the foreground and background colour channels independently. The
+/// true `fg`/`bg` colours are left untouched so truecolor output is unaffected.
+pub fn dither_blocks(blocks: &mut [Block], columns: u32) {
+    if columns == 0 || blocks.is_empty() {
+        return;
+    }
+    let rows = blocks.len() as u32 / columns;
+    let mut fg_error = vec![[0.0f32; 3]; blocks.len()];
+    let mut bg_error = vec![[0.0f32; 3]; blocks.len()];
+    for row in 0..rows {
+        for column in 0..columns {
+            let index = (row * columns + column) as usize;
+            let (cga_fg, residual) = quantize_with_error(blocks[index].fg, fg_error[index]);
+            blocks[index].cga_fg = cga_fg;
+            diffuse_error(&mut fg_error, column, row, columns, rows, residual);
+            if let Some(bg) = blocks[index].bg {
+                let (cga_bg, residual) = quantize_with_error(bg, bg_error[index]);
+                blocks[index].cga_bg = Some(cga_bg);
+                diffuse_error(&mut bg_error, column, row, columns, rows, residual);
             }
-        } else {
-            best = Some(index);
-            best_distance = Some(distance);
         }
     }
-    best.expect("cga color") as u8
 }
 
 struct Match {
@@ -66,6 +297,7 @@ pub struct Codepoint {
 enum FontType {
     IBMVGAType,
     VGA50Type,
+    Custom(String),
 }
 
 pub struct Font {
@@ -73,6 +305,7 @@ pub struct Font {
     pub height: u32,
     size: u32,
     bitmask: Vec<u8>,
+    bit_counts: Vec<u32>,
     font_type: FontType,
 }
 
@@ -81,7 +314,7 @@ impl Font {
         let width = 8;
         let height = (bytes.len() / 256) as u32;
         let size = width * height;
-        let bitmask = bytes
+        let bitmask: Vec<u8> = bytes
             .iter()
             .flat_map(|byte| {
                 (0..8)
@@ -89,11 +322,16 @@ impl Font {
                     .map(move |i| if byte & (1 << i) != 0 { 1 } else { 0 })
             })
             .collect();
+        let bit_counts = bitmask
+            .chunks(size as usize)
+            .map(|glyph| glyph.iter().map(|bit| *bit as u32).sum())
+            .collect();
         Self {
             width,
             height,
             size,
             bitmask,
+            bit_counts,
             font_type,
         }
     }
@@ -108,6 +346,92 @@ impl Font {
         Self::with_bytes(bytes, FontType::VGA50Type)
     }
 
+    /// Loads a raw bitmap font: 256 glyphs, 8 pixels wide, with the height
+    /// inferred from `bytes.len() / 256` exactly as [`Font::with_bytes`] does.
+    /// Suitable for the usual `.F08`/`.F14`/`.F16` dumps as well as any other
+    /// codepage whose glyphs fit that layout.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn error::Error>> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        if bytes.is_empty() || bytes.len() % 256 != 0 {
+            return Err(format!(
+                "font file must contain 256 glyphs of equal height (length a non-zero multiple of 256), got {} bytes",
+                bytes.len()
+            )
+            .into());
+        }
+        Ok(Self::with_bytes(bytes, FontType::Custom(font_name(path))))
+    }
+
+    /// Rasterizes a TrueType font at the requested cell height, producing the
+    /// same 8×height bitmask representation as the embedded fonts. Each of the
+    /// 256 codepoints is rendered to a grayscale coverage buffer and thresholded
+    /// so a bit is set wherever coverage exceeds ~100/255.
+    pub fn from_ttf<P: AsRef<Path>>(
+        path: P,
+        height: u32,
+    ) -> Result<Self, Box<dyn error::Error>> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)?;
+        let face = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())?;
+        let width = 8u32;
+        let baseline = (height as f32 * 0.75).round() as i32;
+        let mut bytes: Vec<u8> = Vec::with_capacity(256 * height as usize);
+        for codepoint in 0u16..256 {
+            let (metrics, coverage) = face.rasterize(CP437[codepoint as usize], height as f32);
+            // Lay the tightly cropped glyph back into an 8×height cell using the
+            // font metrics so the baseline lines up across codepoints.
+            let mut cell = vec![0u8; (width * height) as usize];
+            let x_off = metrics.xmin;
+            let y_off = baseline - metrics.height as i32 - metrics.ymin;
+            for gy in 0..metrics.height {
+                for gx in 0..metrics.width {
+                    let x = gx as i32 + x_off;
+                    let y = gy as i32 + y_off;
+                    if x >= 0 && (x as u32) < width && y >= 0 && (y as u32) < height {
+                        cell[(y as u32 * width + x as u32) as usize] =
+                            coverage[gy * metrics.width + gx];
+                    }
+                }
+            }
+            // Pack each row of 8 coverage values into one byte, MSB first, to
+            // match the `(0..8).rev()` bit order unpacked by `with_bytes`.
+            for y in 0..height {
+                let mut byte = 0u8;
+                for x in 0..8 {
+                    if cell[(y * width + x) as usize] > 100 {
+                        byte |= 1 << (7 - x);
+                    }
+                }
+                bytes.push(byte);
+            }
+        }
+        Ok(Self::with_bytes(bytes, FontType::Custom(font_name(path))))
+    }
+
+    /// Whether this font is a user-supplied one rather than an embedded CP437
+    /// set, i.e. whether it needs to be carried inline in an XBin file.
+    pub fn is_custom(&self) -> bool {
+        matches!(self.font_type, FontType::Custom(_))
+    }
+
+    /// Repacks the expanded bitmask back into one bit per pixel, 8 pixels per
+    /// byte (MSB first), the glyph layout XBin expects for an embedded font.
+    pub fn glyph_bytes(&self) -> Vec<u8> {
+        self.bitmask
+            .chunks(8)
+            .map(|chunk| {
+                let mut byte = 0u8;
+                for (i, bit) in chunk.iter().enumerate() {
+                    if *bit == 1 {
+                        byte |= 1 << (7 - i);
+                    }
+                }
+                byte
+            })
+            .collect()
+    }
+
     fn bits_for_codepoint(&self, codepoint: u8) -> impl Iterator<Item = &u8> {
         let start = codepoint as u32 * self.size;
         let end = start + self.size;
@@ -135,21 +459,25 @@ impl Font {
         }
     }
 
-    fn find_closest_bitmask(&self, other: &[u8], restrict: bool) -> Match {
+    fn find_closest_bitmask(&self, other: &[u8], palette: &[[u8; 4]]) -> Match {
         let mut best = Match {
             codepoint: 0,
             fg: 0,
             bg: 0,
         };
-        let mut best_count = 0;
-        let range: Vec<u8> = if restrict {
-            [32, 176, 177, 178, 219, 220, 221, 222, 223]
-                .into_iter()
-                .collect()
+        let mut best_score = f32::MIN;
+        let size = self.size as f32;
+        // Fraction of the cell occupied by palette entry 1 (the nominal
+        // foreground), and the perceptual contrast between the two colours.
+        let area_ratio = other.iter().filter(|pixel| **pixel == 1).count() as f32 / size;
+        let contrast = if palette.len() >= 2 {
+            let a = srgb_to_oklab(RGB::new(palette[0][0], palette[0][1], palette[0][2]));
+            let b = srgb_to_oklab(RGB::new(palette[1][0], palette[1][1], palette[1][2]));
+            oklab_distance(&a, &b).sqrt()
         } else {
-            (0..=255).collect()
+            0.0
         };
-        for codepoint in range {
+        for codepoint in 0..=255u8 {
             if codepoint == 9
                 || codepoint == 10
                 || codepoint == 13
@@ -163,22 +491,27 @@ impl Font {
                 .zip(other.iter())
                 .map(|(a, b)| if *a == *b { 1 } else { 0 })
                 .sum();
-            if count > best_count {
+            let coverage = self.bit_counts[codepoint as usize] as f32 / size;
+            // When the two colours differ strongly, penalise glyphs whose set-bit
+            // ratio strays from the cell's area ratio; this steers solid cells
+            // toward 219/32 and keeps high-contrast cells from grabbing an
+            // arbitrary glyph that merely happens to match a few bits.
+            let direct_penalty = contrast * size * (coverage - area_ratio).abs();
+            let direct_score = count as f32 - direct_penalty;
+            if direct_score > best_score {
                 best.codepoint = codepoint;
                 best.fg = 1;
                 best.bg = 0;
-                best_count = count;
+                best_score = direct_score;
             }
-            let inverse_count: u32 = self
-                .bits_for_codepoint(codepoint)
-                .zip(other.iter())
-                .map(|(a, b)| if *a == *b { 0 } else { 1 })
-                .sum();
-            if inverse_count > best_count {
+            let inverse_count = self.size - count;
+            let inverse_penalty = contrast * size * ((1.0 - coverage) - area_ratio).abs();
+            let inverse_score = inverse_count as f32 - inverse_penalty;
+            if inverse_score > best_score {
                 best.codepoint = codepoint;
                 best.fg = 0;
                 best.bg = 1;
-                best_count = count;
+                best_score = inverse_score;
             }
         }
         best
@@ -196,10 +529,18 @@ impl Display for FontType {
         match self {
             FontType::IBMVGAType => write!(f, "IBM VGA"),
             FontType::VGA50Type => write!(f, "IBM VGA50"),
+            FontType::Custom(name) => write!(f, "{}", name),
         }
     }
 }
 
+fn font_name(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Custom")
+        .to_string()
+}
+
 pub struct TextSection {
     pub pixels: Vec<u8>,
     pub palette: Vec<[u8; 4]>,
@@ -305,32 +646,69 @@ pub struct Block {
     pub bg: Option<[u8; 4]>,
     pub cga_fg: u8,
     pub cga_bg: Option<u8>,
+    pub xterm_fg: u8,
+    pub xterm_bg: Option<u8>,
     pub codepoint: u8,
     pub column: u32,
     pub row: u32,
 }
 
-pub fn convert_image(
+/// Renders the image as upper-half blocks (codepoint 223, `▀`) instead of
+/// matching glyphs. The source is resized to `columns` wide by `rows * 2` tall
+/// so every character cell maps to two vertically stacked pixels: the top pixel
+/// becomes the foreground and the bottom pixel the background. This skips the
+/// per-cell bitmask fitting entirely, which is faster and sharper for
+/// photographic GIFs.
+pub fn convert_image_half_block(
     image: &DynamicImage,
     font: &Font,
     columns: u32,
-    restrict: bool,
+    xterm256: bool,
 ) -> Vec<Block> {
+    let rows = image.calculate_rows(columns, font.width, font.height);
+    let image = image.resize_exact(columns, rows * 2, FilterType::Lanczos3);
+    let mut blocks = Vec::with_capacity((columns * rows) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let top = image.get_pixel(column, row * 2);
+            let bottom = image.get_pixel(column, row * 2 + 1);
+            let fg = [top[0], top[1], top[2], 255];
+            let bg = [bottom[0], bottom[1], bottom[2], 255];
+            blocks.push(Block {
+                fg,
+                bg: Some(bg),
+                cga_fg: find_closest_cga_color(fg),
+                cga_bg: Some(find_closest_cga_color(bg)),
+                xterm_fg: if xterm256 { find_closest_xterm_color(fg) } else { 0 },
+                xterm_bg: xterm256.then(|| find_closest_xterm_color(bg)),
+                codepoint: 223,
+                column,
+                row,
+            });
+        }
+    }
+    blocks
+}
+
+pub fn convert_image(image: &DynamicImage, font: &Font, columns: u32, xterm256: bool) -> Vec<Block> {
     image
         .as_text_sections(columns, font.width, font.height)
         .map(|section| {
             if section.palette.len() == 1 {
+                let fg = section.palette[0];
                 Block {
-                    fg: section.palette[0],
+                    fg,
                     bg: None,
-                    cga_fg: find_closest_cga_color(section.palette[0]),
+                    cga_fg: find_closest_cga_color(fg),
                     cga_bg: None,
+                    xterm_fg: if xterm256 { find_closest_xterm_color(fg) } else { 0 },
+                    xterm_bg: None,
                     codepoint: 219,
                     column: section.column,
                     row: section.row,
                 }
             } else {
-                let best = font.find_closest_bitmask(&section.pixels, restrict);
+                let best = font.find_closest_bitmask(&section.pixels, &section.palette);
                 let fg = section.palette[best.fg as usize];
                 let bg = section.palette[best.bg as usize];
                 Block {
@@ -338,6 +716,8 @@ pub fn convert_image(
                     bg: Some(bg),
                     cga_fg: find_closest_cga_color(fg),
                     cga_bg: Some(find_closest_cga_color(bg)),
+                    xterm_fg: if xterm256 { find_closest_xterm_color(fg) } else { 0 },
+                    xterm_bg: xterm256.then(|| find_closest_xterm_color(bg)),
                     codepoint: best.codepoint,
                     column: section.column,
                     row: section.row,
@@ -1,23 +1,115 @@
 mod converter;
-use clap::Parser;
-use converter::{convert_image, get_cga_color, Block, Font};
+mod sauce;
+use clap::{Parser, ValueEnum};
+use converter::{
+    convert_image, convert_image_half_block, get_cga_color, get_xterm_color, Block, Font,
+};
 use image::DynamicImage;
+use sauce::Sauce;
 use std::error;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 
-static SAUCE_BYTES: &[u8; 129] = include_bytes!("./sauce.bin");
+#[derive(Clone, ValueEnum)]
+enum Format {
+    /// Escape-sequence ANSI (the default)
+    Ansi,
+    /// Binary Text: two bytes (codepoint, VGA attribute) per cell
+    Bin,
+    /// XBin: attribute data with an optional embedded palette and font
+    Xbin,
+}
+
+/// Binary Text export: a codepoint byte followed by a VGA attribute byte
+/// (`fg | (bg << 4)`) for every cell, laid out `columns × rows` with no
+/// per-cell escape-sequence overhead.
+fn convert_blocks_to_bin(blocks: &[Block]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(blocks.len() * 2);
+    for block in blocks {
+        bytes.push(block.codepoint);
+        let fg = block.cga_fg & 0x0f;
+        let bg = block.cga_bg.unwrap_or(0) & 0x0f;
+        bytes.push(fg | (bg << 4));
+    }
+    bytes
+}
+
+/// XBin export: the `XBIN\x1A` header, an optional palette and glyph bitmap
+/// when a custom font is in use, then the uncompressed attribute data.
+fn convert_blocks_to_xbin(blocks: &[Block], font: &Font, columns: u32) -> Vec<u8> {
+    let rows = (blocks.len() as u32) / columns;
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.extend_from_slice(b"XBIN");
+    bytes.push(0x1a);
+    bytes.extend_from_slice(&(columns as u16).to_le_bytes());
+    bytes.extend_from_slice(&(rows as u16).to_le_bytes());
+    bytes.push(font.height as u8);
+    // bit0 palette, bit1 font, bit3 non-blink (iCE colors).
+    let mut flags = 0x08u8;
+    if font.is_custom() {
+        flags |= 0x01 | 0x02;
+    }
+    bytes.push(flags);
+    if font.is_custom() {
+        bytes.extend_from_slice(&converter::cga_palette_xbin());
+        bytes.extend_from_slice(&font.glyph_bytes());
+    }
+    bytes.extend_from_slice(&convert_blocks_to_bin(blocks));
+    bytes
+}
+
+/// User-supplied SAUCE metadata fields.
+struct SauceMeta {
+    title: String,
+    author: String,
+    group: String,
+    date: String,
+    comments: Vec<String>,
+}
+
+/// Today's date in the SAUCE `YYYYMMDD` format, derived from the system clock
+/// with the usual civil-from-days conversion.
+fn today_yyyymmdd() -> String {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}{:02}{:02}", year, month, day)
+}
 
 fn convert_blocks_to_ans(
     blocks: &Vec<Block>,
     font: &Font,
     columns: u32,
     truecolor: bool,
+    xterm256: bool,
+    meta: &SauceMeta,
 ) -> Vec<u8> {
     let mut ans: Vec<u8> = Vec::new();
     for block in blocks {
+        if xterm256 {
+            ans.append("\x1b[0m".as_bytes().to_vec().as_mut());
+            if let Some(bg) = block.xterm_bg {
+                let bg_string = format!("\x1b[48;5;{}m", bg);
+                ans.append(bg_string.as_bytes().to_vec().as_mut());
+            }
+            let fg_string = format!("\x1b[38;5;{}m", block.xterm_fg);
+            ans.append(fg_string.as_bytes().to_vec().as_mut());
+            ans.push(block.codepoint);
+            continue;
+        }
         ans.append("\x1b[0;".as_bytes().to_vec().as_mut());
         if let Some(bg) = block.cga_bg {
             let bg_string = if bg >= 8 {
@@ -44,14 +136,21 @@ fn convert_blocks_to_ans(
         }
         ans.push(block.codepoint);
     }
-    let mut sauce = SAUCE_BYTES.to_vec();
-    sauce[91..95].copy_from_slice((ans.len() as u32).to_le_bytes().as_ref());
-    sauce[97..99].copy_from_slice((columns as u16).to_le_bytes().as_ref());
     let rows = (blocks.len() as u32) / columns;
-    sauce[99..=100].copy_from_slice((rows as u16).to_le_bytes().as_ref());
-    let font_string = font.to_string();
-    sauce[107..(107 + font_string.len())].copy_from_slice(font_string.as_bytes());
-    ans.append(&mut sauce);
+    let sauce = Sauce {
+        title: meta.title.clone(),
+        author: meta.author.clone(),
+        group: meta.group.clone(),
+        date: meta.date.clone(),
+        file_size: ans.len() as u32,
+        columns: columns as u16,
+        rows: rows as u16,
+        datatype: sauce::DATATYPE_CHARACTER,
+        filetype: sauce::FILETYPE_ANSI,
+        font_name: font.to_string(),
+        comments: meta.comments.clone(),
+    };
+    ans.append(&mut sauce.serialize());
     ans
 }
 
@@ -60,12 +159,18 @@ fn convert_blocks_to_image(
     font: &Font,
     columns: u32,
     truecolor: bool,
+    xterm256: bool,
 ) -> DynamicImage {
     let rows = (blocks.len() as u32) / columns;
     let mut image = DynamicImage::new_rgba8(columns * font.width, rows * font.height);
     for block in blocks {
         let (fg, bg) = if truecolor {
             (block.fg, block.bg)
+        } else if xterm256 {
+            (
+                get_xterm_color(block.xterm_fg),
+                block.xterm_bg.map(get_xterm_color),
+            )
         } else {
             (get_cga_color(block.cga_fg), block.cga_bg.map(get_cga_color))
         };
@@ -86,6 +191,9 @@ struct Cli {
     /// Use 8x8 font
     #[clap(long, action, value_name = "Defaults to 8x16")]
     vga50: bool,
+    /// Load a custom font (raw `.F08`/`.F14`/`.F16` bitmap, or a `.ttf`/`.otf`)
+    #[clap(long, value_name = "PATH")]
+    font: Option<PathBuf>,
     /// Number of columns
     #[clap(long, value_name = "1 to 65535", default_value = "80")]
     columns: u16,
@@ -100,6 +208,33 @@ struct Cli {
         value_name = "Defaults to CGA colors"
     )]
     truecolor: bool,
+    /// Map colours to the xterm 256-color palette
+    #[clap(long, action, value_name = "256-colour SGR output", conflicts_with = "truecolor")]
+    xterm256: bool,
+    /// Render upper-half blocks for 2x vertical resolution
+    #[clap(long, action, value_name = "Two pixels per character")]
+    half_block: bool,
+    /// Floyd-Steinberg dithering of the CGA colour mapping
+    #[clap(long, action, value_name = "Improves 16-colour gradients")]
+    dither: bool,
+    /// SAUCE title (up to 35 characters)
+    #[clap(long, value_name = "TITLE", default_value = "")]
+    title: String,
+    /// SAUCE author (up to 20 characters)
+    #[clap(long, value_name = "AUTHOR", default_value = "")]
+    author: String,
+    /// SAUCE group (up to 20 characters)
+    #[clap(long, value_name = "GROUP", default_value = "")]
+    group: String,
+    /// SAUCE date (YYYYMMDD); defaults to today
+    #[clap(long, value_name = "YYYYMMDD", default_value = "")]
+    date: String,
+    /// SAUCE comment line (repeat for multiple 64-character lines)
+    #[clap(long, value_name = "COMMENT")]
+    comment: Vec<String>,
+    /// Output format
+    #[clap(long, value_enum, default_value = "ansi")]
+    format: Format,
     #[clap(value_name = "INPUT")]
     input: PathBuf,
     #[clap(value_name = "OUTPUT")]
@@ -109,13 +244,77 @@ struct Cli {
 fn convert(cli: Cli) -> Result<(), Box<dyn error::Error>> {
     let path = Path::new(&cli.input);
     let image = image::open(path)?;
-    let font = if cli.vga50 {
+    let font = if let Some(font_path) = &cli.font {
+        let is_ttf = font_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf"))
+            .unwrap_or(false);
+        if is_ttf {
+            let height = if cli.vga50 { 8 } else { 16 };
+            Font::from_ttf(font_path, height)?
+        } else {
+            Font::from_file(font_path)?
+        }
+    } else if cli.vga50 {
         Font::vga50()
     } else {
         Font::ibm_vga()
     };
-    let blocks = convert_image(&image, &font, cli.columns as u32);
-    let bytes = convert_blocks_to_ans(&blocks, &font, cli.columns as u32, cli.truecolor);
+    let blocks = if cli.half_block {
+        convert_image_half_block(&image, &font, cli.columns as u32, cli.xterm256)
+    } else {
+        convert_image(&image, &font, cli.columns as u32, cli.xterm256)
+    };
+    let mut blocks = blocks;
+    if cli.dither {
+        converter::dither_blocks(&mut blocks, cli.columns as u32);
+    }
+    let date = if cli.date.is_empty() {
+        today_yyyymmdd()
+    } else {
+        cli.date
+    };
+    let meta = SauceMeta {
+        title: cli.title,
+        author: cli.author,
+        group: cli.group,
+        date,
+        comments: cli.comment,
+    };
+    let bytes = match cli.format {
+        Format::Ansi => convert_blocks_to_ans(
+            &blocks,
+            &font,
+            cli.columns as u32,
+            cli.truecolor,
+            cli.xterm256,
+            &meta,
+        ),
+        Format::Bin => {
+            let mut bytes = convert_blocks_to_bin(&blocks);
+            let rows = (blocks.len() as u32) / cli.columns as u32;
+            // Binary Text carries its width only through the SAUCE record, so
+            // append one to keep the column count recoverable by ANSI editors.
+            let sauce = Sauce {
+                title: meta.title.clone(),
+                author: meta.author.clone(),
+                group: meta.group.clone(),
+                date: meta.date.clone(),
+                file_size: bytes.len() as u32,
+                columns: cli.columns,
+                rows: rows as u16,
+                // Binary Text carries its width in FileType (chars / 2), not TInfo1.
+                datatype: sauce::DATATYPE_BINARY_TEXT,
+                filetype: (cli.columns / 2) as u8,
+                font_name: font.to_string(),
+                comments: meta.comments.clone(),
+            };
+            bytes.append(&mut sauce.serialize());
+            bytes
+        }
+        Format::Xbin => convert_blocks_to_xbin(&blocks, &font, cli.columns as u32),
+    };
     let mut out_path = PathBuf::from(&cli.output);
     let file = File::create(&out_path)?;
     let mut writer = BufWriter::new(file);
@@ -123,7 +322,8 @@ fn convert(cli: Cli) -> Result<(), Box<dyn error::Error>> {
     writer.flush()?;
     println!("Wrote {:?}", out_path);
     if cli.image {
-        let image = convert_blocks_to_image(&blocks, &font, cli.columns as u32, cli.truecolor);
+        let image =
+            convert_blocks_to_image(&blocks, &font, cli.columns as u32, cli.truecolor, cli.xterm256);
         out_path.set_extension("ans.png");
         image.save(&out_path)?;
         println!("Wrote {:?}", out_path);
@@ -0,0 +1,64 @@
+//! Serialization of the 128-byte SAUCE metadata record (and its optional
+//! `COMNT` comment block) from typed fields, replacing the previous approach of
+//! poking raw byte offsets in a hardcoded blob.
+
+/// DataType `Character`.
+pub const DATATYPE_CHARACTER: u8 = 1;
+/// DataType `BinaryText`.
+pub const DATATYPE_BINARY_TEXT: u8 = 5;
+/// FileType `ANSi` within the `Character` datatype.
+pub const FILETYPE_ANSI: u8 = 1;
+/// TFlags: non-blink (iCE colors) enabled.
+const TFLAGS_ICE_COLORS: u8 = 0x01;
+
+pub struct Sauce {
+    pub title: String,
+    pub author: String,
+    pub group: String,
+    pub date: String,
+    pub file_size: u32,
+    pub columns: u16,
+    pub rows: u16,
+    pub datatype: u8,
+    pub filetype: u8,
+    pub font_name: String,
+    pub comments: Vec<String>,
+}
+
+impl Sauce {
+    /// Serializes the record as it should be appended to the ANSI stream: an
+    /// EOF marker, the optional `COMNT` block, then the 128-byte record itself.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.push(0x1a);
+        if !self.comments.is_empty() {
+            bytes.extend_from_slice(b"COMNT");
+            for comment in &self.comments {
+                write_padded(&mut bytes, comment, 64, b' ');
+            }
+        }
+        bytes.extend_from_slice(b"SAUCE00");
+        write_padded(&mut bytes, &self.title, 35, b' ');
+        write_padded(&mut bytes, &self.author, 20, b' ');
+        write_padded(&mut bytes, &self.group, 20, b' ');
+        write_padded(&mut bytes, &self.date, 8, b' ');
+        bytes.extend_from_slice(&self.file_size.to_le_bytes());
+        bytes.push(self.datatype);
+        bytes.push(self.filetype);
+        bytes.extend_from_slice(&self.columns.to_le_bytes());
+        bytes.extend_from_slice(&self.rows.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.push(self.comments.len() as u8);
+        bytes.push(TFLAGS_ICE_COLORS);
+        write_padded(&mut bytes, &self.font_name, 22, 0);
+        bytes
+    }
+}
+
+fn write_padded(bytes: &mut Vec<u8>, text: &str, len: usize, pad: u8) {
+    let source = text.as_bytes();
+    for i in 0..len {
+        bytes.push(source.get(i).copied().unwrap_or(pad));
+    }
+}